@@ -1,18 +1,26 @@
-use std::fs::{File, create_dir_all, read_dir};
-use std::io::Write;
-use std::path::{Path, PathBuf};
+mod storage;
+
+use std::io::Cursor;
 use std::process::{self};
+use std::sync::Arc;
 use std::sync::Mutex;
 use std::sync::atomic::{AtomicUsize, Ordering};
 
 use anyhow::{Context, Result};
+use arrow::array::{Array, BinaryArray, StringArray, StructArray};
+use arrow::datatypes::{DataType, Field, Schema};
 use arrow::ipc::reader::StreamReader;
 use arrow::record_batch::RecordBatch;
+use bytes::Bytes;
 use clap::{ArgAction, Parser, ValueEnum};
 use parquet::arrow::ArrowWriter;
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use parquet::basic::{BrotliLevel, Compression, ZstdLevel};
 use parquet::file::properties::WriterProperties;
 use polars::prelude::*;
 use rayon::{ThreadPoolBuilder, prelude::*};
+use storage::Location;
+use tokio::runtime::Handle;
 
 #[derive(Clone, Debug, Copy, PartialEq, Eq, ValueEnum)]
 enum Format {
@@ -20,224 +28,518 @@ enum Format {
     Parquet,
 }
 
+#[derive(Clone, Debug, Copy, PartialEq, Eq, ValueEnum)]
+enum MetadataFormat {
+    Csv,
+    Jsonl,
+    Parquet,
+}
+
+#[derive(Clone, Debug, Copy, PartialEq, Eq, ValueEnum)]
+enum MetadataCompression {
+    Snappy,
+    Zstd,
+    Brotli,
+}
+
+impl From<MetadataCompression> for Compression {
+    fn from(compression: MetadataCompression) -> Self {
+        match compression {
+            MetadataCompression::Snappy => Compression::SNAPPY,
+            MetadataCompression::Zstd => Compression::ZSTD(ZstdLevel::default()),
+            MetadataCompression::Brotli => Compression::BROTLI(BrotliLevel::default()),
+        }
+    }
+}
+
 #[derive(Parser, Debug)]
 #[command(version, long_about = None)]
 struct Args {
-    /// The path to the input file
+    /// The input file. Accepts a local path or a URI such as
+    /// `s3://bucket/prefix/shard.parquet` or `gs://bucket/shard.arrow`.
     #[arg(long, conflicts_with = "input_dir")]
-    input: Option<PathBuf>,
+    input: Option<String>,
 
-    /// The path to a directory with input files
+    /// A directory (local or `s3://`/`gs://`/... prefix) containing input
+    /// files.
     #[arg(long, conflicts_with = "input")]
-    input_dir: Option<PathBuf>,
+    input_dir: Option<String>,
 
     /// File format
     #[arg(long)]
     #[clap(value_enum, default_value_t = Format::Parquet)]
     format: Format,
 
-    /// The path to the output files
+    /// Where extracted audio files are written. Local path or object store
+    /// URI.
     #[arg(long)]
-    output: PathBuf,
+    output: String,
 
-    /// Number of threads to use for processing
+    /// Number of threads to use for extracting rows within a shard
     #[arg(long, default_value_t = 3)]
     threads: usize,
 
-    /// CSV file where transcriptions should be written
+    /// Number of shards to decode concurrently when using --input-dir. Shard
+    /// decoding is largely I/O-bound, so this can usually be set higher than
+    /// --threads.
+    #[arg(long, default_value_t = 4)]
+    file_threads: usize,
+
+    /// File where transcriptions should be written. Local path or object
+    /// store URI.
     #[arg(long, action = ArgAction::Set)]
-    metadata_file: Option<PathBuf>,
+    metadata_file: Option<String>,
+
+    /// Format for the metadata sink: csv mangles transcriptions that contain
+    /// commas/quotes/newlines, jsonl is delimiter-safe and streamable,
+    /// parquet round-trips losslessly into downstream Arrow pipelines.
+    #[arg(long, value_enum, default_value_t = MetadataFormat::Csv)]
+    metadata_format: MetadataFormat,
+
+    /// Compression codec used when --metadata-format=parquet.
+    #[arg(long, value_enum, default_value_t = MetadataCompression::Snappy)]
+    metadata_compression: MetadataCompression,
+
+    /// Column holding the audio data. May be a struct column (HuggingFace's
+    /// `audio: {bytes, path}` layout); if it isn't a struct, the layout is
+    /// treated as flat and the raw audio bytes are read from the top-level
+    /// column named by --bytes-field.
+    #[arg(long, default_value = "audio")]
+    audio_column: String,
+
+    /// Field holding raw audio bytes: a child field of --audio-column if that
+    /// column is a struct, otherwise the name of a flat binary column.
+    #[arg(long, default_value = "bytes")]
+    bytes_field: String,
+
+    /// Field holding the original audio file name, used to pick an output
+    /// extension. A child field of --audio-column if that column is a struct
+    /// and has such a field, otherwise a top-level column of this name.
+    #[arg(long, default_value = "path")]
+    path_field: String,
+
+    /// Column holding transcriptions. If a shard's schema has no such column,
+    /// audio is still extracted and the transcription metadata field is left
+    /// blank for that shard.
+    #[arg(long, default_value = "transcription")]
+    transcription_column: String,
 }
 
-fn arrow_to_parquet(filename: &Path) -> Result<DataFrame> {
-    let file = File::open(filename)
-        .with_context(|| format!("Failed to open arrow file: {}", filename.display()))?;
-    let reader =
-        StreamReader::try_new(file, None).context("Failed to create arrow stream reader")?;
-
-    let batches: Vec<RecordBatch> = reader
-        .collect::<std::result::Result<_, _>>()
-        .context("Failed to collect record batches from arrow file")?;
-    let df = batches_to_parquet(&batches)
-        .context("Failed to convert arrow batches to parquet for DataFrame")?;
-
-    Ok(df)
+/// Column/field name overrides, so datasets that don't use the HuggingFace
+/// `audio: {bytes, path}` / `transcription` layout can still be processed.
+struct ColumnNames {
+    audio: String,
+    bytes_field: String,
+    path_field: String,
+    transcription: String,
 }
 
-fn batches_to_parquet(batches: &[RecordBatch]) -> Result<DataFrame> {
-    // In-memory buffer to avoid writing to a temporary file on disk
-    let tmp_file = tempfile::tempfile()?;
-
-    // Write the batches to the file
-    let props = WriterProperties::builder().build();
-    let mut writer = ArrowWriter::try_new(tmp_file, batches[0].schema(), Some(props))?;
+impl From<&Args> for ColumnNames {
+    fn from(args: &Args) -> Self {
+        Self {
+            audio: args.audio_column.clone(),
+            bytes_field: args.bytes_field.clone(),
+            path_field: args.path_field.clone(),
+            transcription: args.transcription_column.clone(),
+        }
+    }
+}
 
-    for batch in batches {
-        writer.write(batch)?;
-    } // writer goes out of scope and finishes writing
+/// Lazily decode `bytes` into a stream of `RecordBatch`es, without ever
+/// materializing the whole file as a single `DataFrame`. `StreamReader`
+/// already yields arrow batches one at a time; `ParquetRecordBatchReaderBuilder`
+/// does the same for parquet row groups.
+fn record_batches(format: Format, bytes: Bytes) -> Result<Box<dyn Iterator<Item = Result<RecordBatch>>>> {
+    match format {
+        Format::Arrow => {
+            let reader = StreamReader::try_new(Cursor::new(bytes), None)
+                .context("Failed to create arrow stream reader")?;
+            Ok(Box::new(
+                reader.map(|batch| batch.context("Failed to read record batch from arrow stream")),
+            ))
+        }
+        Format::Parquet => {
+            let reader = ParquetRecordBatchReaderBuilder::try_new(bytes)
+                .context("Failed to open parquet reader")?
+                .build()
+                .context("Failed to build parquet batch reader")?;
+            Ok(Box::new(
+                reader.map(|batch| batch.context("Failed to read record batch from parquet file")),
+            ))
+        }
+    }
+}
 
-    let tmp_file = writer.into_inner()?;
+fn write_file(location: &Location, data: &[u8], handle: &Handle) -> Result<()> {
+    // Skip if the file already exists.
+    if handle.block_on(location.exists())? {
+        return Ok(());
+    }
 
-    // Read in parquet file and unnest the audio column
-    let df = ParquetReader::new(tmp_file)
-        .with_columns(Some(vec!["audio".to_string(), "transcription".to_string()]))
-        .finish()?
-        .unnest(["audio"])?;
+    handle.block_on(location.put_bytes(Bytes::copy_from_slice(data)))?;
 
-    Ok(df)
+    Ok(())
 }
 
-fn read_parquet(filename: &Path) -> Result<DataFrame> {
-    let file = File::open(filename)
-        .with_context(|| format!("Failed to open parquet file: {}", filename.display()))?;
-
-    let df = ParquetReader::new(file)
-        .with_columns(Some(vec!["audio".to_string(), "transcription".to_string()]))
-        .finish()
-        .context("Failed to read parquet file into DataFrame")?
-        .unnest(["audio"])?;
+/// Look up the audio bytes and (optional) original path arrays, honoring the
+/// configured column/field names. `--audio-column` may be a struct (in which
+/// case `--bytes-field` is its child, and `--path-field` is its child or,
+/// failing that, a top-level column) or, for flat layouts such as a bare
+/// `audio_bytes` column, any non-struct column, in which case both
+/// `--bytes-field` and `--path-field` are looked up as top-level columns.
+fn resolve_audio_arrays<'a>(
+    batch: &'a RecordBatch,
+    columns: &ColumnNames,
+) -> Result<(&'a BinaryArray, Option<&'a StringArray>)> {
+    let audio_column = batch.column_by_name(&columns.audio).with_context(|| {
+        format!(
+            "Batch is missing the configured audio column '{}'",
+            columns.audio
+        )
+    })?;
 
-    Ok(df)
-}
+    if let Some(audio_struct) = audio_column.as_any().downcast_ref::<StructArray>() {
+        let bytes_array = audio_struct
+            .column_by_name(&columns.bytes_field)
+            .with_context(|| {
+                format!(
+                    "'{}' struct is missing a '{}' field",
+                    columns.audio, columns.bytes_field
+                )
+            })?
+            .as_any()
+            .downcast_ref::<BinaryArray>()
+            .with_context(|| {
+                format!("'{}.{}' is not a binary array", columns.audio, columns.bytes_field)
+            })?;
 
-fn write_file(filename: &Path, data: &[u8]) -> Result<()> {
-    // Skip if the file already exists. Using `Path::try_exists` is slightly more robust.
-    if filename.try_exists()? {
-        return Ok(());
-    }
+        // Prefer the struct's own child field, but fall back to a top-level
+        // column of the same name (e.g. a sibling `file_name` column) when
+        // the struct doesn't carry a path itself.
+        let path_array = audio_struct
+            .column_by_name(&columns.path_field)
+            .and_then(|c| c.as_any().downcast_ref::<StringArray>())
+            .or_else(|| {
+                batch
+                    .column_by_name(&columns.path_field)
+                    .and_then(|c| c.as_any().downcast_ref::<StringArray>())
+            });
+
+        Ok((bytes_array, path_array))
+    } else {
+        // `--audio-column` isn't a struct; it's just used to detect the flat
+        // layout here. The actual bytes live in whatever top-level column
+        // `--bytes-field` names, which may differ from `--audio-column`.
+        let bytes_array = batch
+            .column_by_name(&columns.bytes_field)
+            .with_context(|| {
+                format!(
+                    "'{}' is not a struct, and no top-level '{}' binary column was found",
+                    columns.audio, columns.bytes_field
+                )
+            })?
+            .as_any()
+            .downcast_ref::<BinaryArray>()
+            .with_context(|| format!("'{}' is not a binary array", columns.bytes_field))?;
 
-    // Write the file
-    let mut file = File::create(filename)?;
-    file.write_all(data)?;
+        let path_array = batch
+            .column_by_name(&columns.path_field)
+            .and_then(|c| c.as_any().downcast_ref::<StringArray>());
 
-    Ok(())
+        Ok((bytes_array, path_array))
+    }
 }
 
-fn process_file(
-    filename: &Path,
-    format: Format,
-    output_dir: &Path,
-    metadata_records: &Mutex<Vec<(String, String)>>,
+/// Extract every row of a single batch: write its audio bytes out under
+/// `output_dir` and append its transcription to `metadata_records`. The
+/// batch is held by the caller only for the duration of this call, so this
+/// avoids the old 3x-materialization (temp parquet file + reread) of the
+/// whole shard. Peak memory is still the shard's encoded bytes (held by
+/// `process_file` for the whole decode loop) plus one decoded batch, not a
+/// single batch in isolation.
+fn process_batch(
+    batch: &RecordBatch,
+    output_dir: &Location,
+    metadata_records: &Mutex<Vec<(String, Option<String>)>>,
+    handle: &Handle,
+    row_pool: &rayon::ThreadPool,
+    columns: &ColumnNames,
+    row_counter: &AtomicUsize,
 ) -> Result<usize> {
-    // Convert the file to a DataFrame
-    let df = match format {
-        Format::Arrow => arrow_to_parquet(filename)
-            .with_context(|| format!("Error processing arrow file {}", filename.display()))?,
-        Format::Parquet => read_parquet(filename)
-            .with_context(|| format!("Error processing parquet file {}", filename.display()))?,
-    };
+    let (bytes_array, path_array) = resolve_audio_arrays(batch, columns)?;
+
+    // Absent rather than erroring: datasets that don't carry transcriptions
+    // still get their audio extracted.
+    let transcription_array = batch
+        .column_by_name(&columns.transcription)
+        .and_then(|c| c.as_any().downcast_ref::<StringArray>());
+
+    let num_rows = batch.num_rows();
+
+    // Row-level parallelism is pinned to `row_pool` (sized by --threads) so it
+    // stays independent of however many shards are being decoded concurrently
+    // by the caller's file-level pool.
+    //
+    // Writes now go through `object_store`, where transient network errors
+    // are routine. Each row's outcome is collected independently rather than
+    // via a single `collect::<Result<Vec<_>>>()`, so one row's write failure
+    // can't discard its already-written siblings' metadata, and doesn't stop
+    // the rest of the shard's batches in `process_file` — it's logged and
+    // skipped instead, the same way a whole failed file is logged and
+    // skipped in `main`.
+    let row_results: Vec<Result<Option<(String, Option<String>)>>> = row_pool.install(|| {
+        (0..num_rows)
+            .into_par_iter()
+            .map(|i| -> Result<Option<(String, Option<String>)>> {
+                if bytes_array.is_null(i) {
+                    return Ok(None);
+                }
 
-    // Extract the series from the DataFrame
-    let path_series = df.column("path")?.str()?;
-    let array_series = df.column("bytes")?.binary()?;
-    let transcription_series = df.column("transcription")?.str()?;
-
-    let num_rows = df.height();
-
-    let records: Vec<_> = (0..num_rows)
-        .into_par_iter()
-        .filter_map(|i| {
-            if let (Some(path_val), Some(transcription), Some(array_series_inner)) = (
-                path_series.get(i),
-                transcription_series.get(i),
-                array_series.get(i),
-            ) {
-                Some((path_val, transcription, array_series_inner))
-            } else {
+                let audio_filename_str = match path_array {
+                    Some(path_array) if !path_array.is_null(i) => {
+                        let original_path = std::path::Path::new(path_array.value(i));
+                        let file_stem = original_path.file_stem().unwrap_or_default();
+                        let extension = original_path.extension().unwrap_or_default();
+                        format!(
+                            "{}.{}",
+                            file_stem.to_string_lossy(),
+                            extension.to_string_lossy()
+                        )
+                    }
+                    // No usable path column: fall back to a unique, numbered
+                    // name. Numbers are handed out in whatever order rows are
+                    // processed, so they can differ between runs.
+                    _ => format!("row_{:08}.bin", row_counter.fetch_add(1, Ordering::SeqCst)),
+                };
+
+                let audio_location = output_dir.join(&audio_filename_str);
+                write_file(&audio_location, bytes_array.value(i), handle)
+                    .with_context(|| format!("Failed to write {audio_location}"))?;
+
+                let transcription = transcription_array
+                    .filter(|a| !a.is_null(i))
+                    .map(|a| a.value(i).to_string());
+
+                Ok(Some((audio_filename_str, transcription)))
+            })
+            .collect()
+    });
+
+    let local_metadata: Vec<(String, Option<String>)> = row_results
+        .into_iter()
+        .filter_map(|result| match result {
+            Ok(record) => record,
+            Err(e) => {
+                eprintln!("Error processing row: {e:?}");
                 None
             }
         })
         .collect();
 
-    let local_metadata: Vec<(String, String)> = records
-        .par_iter()
-        .map(|(path_val, transcription, array_series_inner)| {
-            let original_path = Path::new(path_val);
-            let file_stem = original_path.file_stem().unwrap_or_default();
-            let extension = original_path.extension().unwrap_or_default();
-
-            let audio_filename_str = format!(
-                "{}.{}",
-                file_stem.to_string_lossy(),
-                extension.to_string_lossy()
-            );
-            let audio_filename = output_dir.join(&audio_filename_str);
-            let audio_data: &[u8] = array_series_inner;
-            write_file(&audio_filename, audio_data).expect("Failed to write audio file");
-
-            (audio_filename_str, transcription.to_string())
-        })
-        .collect();
-
     metadata_records.lock().unwrap().extend(local_metadata);
 
     Ok(num_rows)
 }
 
+fn process_file(
+    location: &Location,
+    format: Format,
+    output_dir: &Location,
+    metadata_records: &Mutex<Vec<(String, Option<String>)>>,
+    handle: &Handle,
+    row_pool: &rayon::ThreadPool,
+    columns: &ColumnNames,
+    row_counter: &AtomicUsize,
+) -> Result<usize> {
+    let bytes = handle
+        .block_on(location.get_bytes())
+        .with_context(|| format!("Failed to fetch {location}"))?;
+
+    let mut num_rows = 0;
+    for batch in record_batches(format, bytes)
+        .with_context(|| format!("Error processing file {location}"))?
+    {
+        let batch = batch.with_context(|| format!("Error processing file {location}"))?;
+        num_rows += process_batch(
+            &batch,
+            output_dir,
+            metadata_records,
+            handle,
+            row_pool,
+            columns,
+            row_counter,
+        )
+        .with_context(|| format!("Error processing file {location}"))?;
+        // `batch` is dropped here, before the next one is decoded.
+    }
+
+    Ok(num_rows)
+}
+
+/// Serialize the collected `(file_name, transcription)` metadata and write it
+/// to `location` in the requested format.
+fn write_metadata(
+    records: &[(String, Option<String>)],
+    format: MetadataFormat,
+    compression: MetadataCompression,
+    location: &Location,
+    handle: &Handle,
+) -> Result<()> {
+    let buf = match format {
+        MetadataFormat::Csv => {
+            let mut df = DataFrame::new(vec![
+                Column::new(
+                    "file_name".into(),
+                    records.iter().map(|(f, _)| f.as_str()).collect::<Vec<_>>(),
+                ),
+                Column::new(
+                    "transcription".into(),
+                    records
+                        .iter()
+                        .map(|(_, t)| t.as_deref().unwrap_or_default())
+                        .collect::<Vec<_>>(),
+                ),
+            ])?;
+
+            let mut buf = Vec::new();
+            CsvWriter::new(&mut buf).finish(&mut df)?;
+            buf
+        }
+        MetadataFormat::Jsonl => {
+            let mut buf = Vec::new();
+            for (file_name, transcription) in records {
+                serde_json::to_writer(
+                    &mut buf,
+                    &serde_json::json!({
+                        "file_name": file_name,
+                        "transcription": transcription,
+                    }),
+                )
+                .context("Failed to serialize metadata record to JSON")?;
+                buf.push(b'\n');
+            }
+            buf
+        }
+        MetadataFormat::Parquet => {
+            let schema = Arc::new(Schema::new(vec![
+                Field::new("file_name", DataType::Utf8, false),
+                Field::new("transcription", DataType::Utf8, true),
+            ]));
+
+            let batch = RecordBatch::try_new(
+                Arc::clone(&schema),
+                vec![
+                    Arc::new(StringArray::from(
+                        records.iter().map(|(f, _)| f.as_str()).collect::<Vec<_>>(),
+                    )),
+                    Arc::new(StringArray::from(
+                        records.iter().map(|(_, t)| t.as_deref()).collect::<Vec<_>>(),
+                    )),
+                ],
+            )
+            .context("Failed to build metadata record batch")?;
+
+            let props = WriterProperties::builder()
+                .set_compression(compression.into())
+                .build();
+
+            let mut buf = Vec::new();
+            let mut writer = ArrowWriter::try_new(&mut buf, schema, Some(props))?;
+            writer.write(&batch)?;
+            writer.close()?;
+            buf
+        }
+    };
+
+    handle.block_on(location.put_bytes(Bytes::from(buf)))?;
+
+    Ok(())
+}
+
 fn main() -> Result<()> {
     let args = Args::parse();
 
-    // Configure the global thread pool for Rayon
-    ThreadPoolBuilder::new()
+    // Two independent pools: one bounds how many rows of a shard are
+    // extracted concurrently, the other bounds how many shards are decoded
+    // concurrently. Keeping them separate lets each be tuned for its own
+    // bottleneck (per-row audio writes vs. shard fetches and decoding).
+    let row_pool = ThreadPoolBuilder::new()
         .num_threads(args.threads)
-        .build_global()?;
+        .build()?;
+    let file_pool = ThreadPoolBuilder::new()
+        .num_threads(args.file_threads)
+        .build()?;
 
     if !args.input.is_some() && !args.input_dir.is_some() {
         eprintln!("Either --input or --input-dir must be provided.");
         process::exit(1);
     }
 
-    // Create the output folder if it doesn't exist
-    create_dir_all(&args.output).with_context(|| {
-        format!(
-            "Failed to create output directory: {}",
-            args.output.display()
-        )
-    })?;
+    // Object store access is async; `process_file` and `write_file` drive it
+    // synchronously from Rayon's worker threads via this runtime.
+    let runtime = tokio::runtime::Runtime::new()?;
+    let handle = runtime.handle().clone();
 
+    let output = Location::parse(&args.output)?;
+    output.ensure_dir()?;
+
+    let columns = ColumnNames::from(&args);
+    let row_counter = AtomicUsize::new(0);
     let metadata_records = Mutex::new(Vec::new());
 
-    if let Some(input_file) = args.input {
-        if !input_file.is_file() {
-            eprintln!("Input is not a file: {}", input_file.display());
-            process::exit(1);
-        }
-        println!("Processing file: {}...", input_file.display());
-        let rows = process_file(&input_file, args.format, &args.output, &metadata_records)?;
+    if let Some(input) = args.input {
+        let location = Location::parse(&input)?;
+        println!("Processing file: {input}...");
+        let rows = process_file(
+            &location,
+            args.format,
+            &output,
+            &metadata_records,
+            &handle,
+            &row_pool,
+            &columns,
+            &row_counter,
+        )?;
         println!("Total number of rows processed: {}", rows);
     }
 
     if let Some(input_dir) = args.input_dir {
-        if !input_dir.is_dir() {
-            eprintln!(
-                "Input directory does not exist or is not a directory: {}",
-                input_dir.display()
-            );
-            process::exit(1);
-        }
-
-        let files_to_process: Vec<_> = read_dir(input_dir)?
-            .filter_map(Result::ok)
-            .filter(|entry| {
-                entry.path().is_file()
-                    && entry // TODO: this is not correct, should be based on format
-                        .path()
-                        .extension()
-                        .is_some_and(|ext| ext == "parquet" || ext == "arrow")
+        let dir = Location::parse(&input_dir)?;
+
+        let files_to_process: Vec<_> = handle
+            .block_on(dir.list())?
+            .into_iter()
+            // TODO: this is not correct, should be based on format
+            .filter(|location| {
+                location
+                    .extension()
+                    .is_some_and(|ext| ext == "parquet" || ext == "arrow")
             })
             .collect();
 
         let total_rows = AtomicUsize::new(0);
 
-        files_to_process.into_iter().for_each(|entry| {
-            let path = entry.path();
-            println!("Processing file: {}...", path.display());
-            match process_file(&path, args.format, &args.output, &metadata_records) {
-                Ok(rows) => {
-                    total_rows.fetch_add(rows, Ordering::SeqCst);
+        // Shards are decoded concurrently on `file_pool`; each shard's rows
+        // are then extracted concurrently on `row_pool`.
+        file_pool.install(|| {
+            files_to_process.into_par_iter().for_each(|location| {
+                println!("Processing file: {location}...");
+                match process_file(
+                    &location,
+                    args.format,
+                    &output,
+                    &metadata_records,
+                    &handle,
+                    &row_pool,
+                    &columns,
+                    &row_counter,
+                ) {
+                    Ok(rows) => {
+                        total_rows.fetch_add(rows, Ordering::SeqCst);
+                    }
+                    Err(e) => eprintln!("Error processing file {location}: {e}"),
                 }
-                Err(e) => eprintln!("Error processing file {}: {}", entry.path().display(), e),
-            }
+            });
         });
 
         println!(
@@ -247,27 +549,17 @@ fn main() -> Result<()> {
     }
 
     if let Some(metadata_file_path) = args.metadata_file {
-        println!("Writing metadata to {}...", metadata_file_path.display());
+        println!("Writing metadata to {metadata_file_path}...");
         let records = metadata_records.into_inner().unwrap();
         if !records.is_empty() {
-            let mut df = DataFrame::new(vec![
-                Column::new(
-                    "file_name".into(),
-                    records.iter().map(|(f, _)| f.as_str()).collect::<Vec<_>>(),
-                ),
-                Column::new(
-                    "transcription".into(),
-                    records.iter().map(|(_, t)| t.as_str()).collect::<Vec<_>>(),
-                ),
-            ])?;
-
-            let mut file = File::create(&metadata_file_path).with_context(|| {
-                format!(
-                    "Failed to create metadata file: {}",
-                    metadata_file_path.display()
-                )
-            })?;
-            CsvWriter::new(&mut file).finish(&mut df)?;
+            let metadata_location = Location::parse(&metadata_file_path)?;
+            write_metadata(
+                &records,
+                args.metadata_format,
+                args.metadata_compression,
+                &metadata_location,
+                &handle,
+            )?;
         }
     }
 
@@ -0,0 +1,155 @@
+//! A thin local/object-store abstraction so the same code path can read and
+//! write local files as well as URIs like `s3://bucket/prefix/...`,
+//! `gs://bucket/...` or `az://container/...`.
+
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use bytes::Bytes;
+use object_store::local::LocalFileSystem;
+use object_store::path::Path as StorePath;
+use object_store::{ObjectStore, parse_url};
+use url::Url;
+
+/// A resolved `(store, path)` pair pointing at a single object, local or
+/// remote. Cloning is cheap: the store is reference-counted.
+#[derive(Clone)]
+pub struct Location {
+    pub store: Arc<dyn ObjectStore>,
+    pub path: StorePath,
+    is_local: bool,
+}
+
+impl Location {
+    /// Parse a CLI-supplied location. Anything that parses as a URL with a
+    /// scheme other than `file` is handed to `object_store::parse_url`, which
+    /// understands `s3://`, `gs://`, `az://`/`abfs://` and friends; everything
+    /// else is treated as a path on the local filesystem.
+    pub fn parse(raw: &str) -> Result<Self> {
+        if let Ok(url) = Url::parse(raw) {
+            if url.scheme() != "file" {
+                let (store, path) = parse_url(&url)
+                    .with_context(|| format!("Failed to parse object store location: {raw}"))?;
+                return Ok(Self {
+                    store: Arc::from(store),
+                    path,
+                    is_local: false,
+                });
+            }
+        }
+
+        // `StorePath::from_filesystem_path` canonicalizes the path, which
+        // requires it to already exist on disk. That's wrong here: the
+        // normal case for `--output`/`--metadata-file` is a path that
+        // doesn't exist yet (it's about to be created/written). Resolve to
+        // an absolute path lexically instead, without touching the
+        // filesystem.
+        let absolute = std::path::absolute(raw)
+            .with_context(|| format!("Failed to resolve local path: {raw}"))?;
+        let path = StorePath::from_absolute_path(&absolute)
+            .with_context(|| format!("Failed to resolve local path: {raw}"))?;
+        Ok(Self {
+            store: Arc::new(LocalFileSystem::new()),
+            path,
+            is_local: true,
+        })
+    }
+
+    /// Build a location for `child` underneath this one, e.g. turning an
+    /// output directory into the location of one extracted audio file.
+    pub fn join(&self, child: &str) -> Self {
+        Self {
+            store: Arc::clone(&self.store),
+            path: self.path.child(child),
+            is_local: self.is_local,
+        }
+    }
+
+    /// Create the directory this location points at, if it is on the local
+    /// filesystem. Object stores have no directories to create.
+    pub fn ensure_dir(&self) -> Result<()> {
+        if self.is_local {
+            // The store path drops the leading `/`, so turn it back into an
+            // absolute filesystem path before creating it.
+            let dir = Url::parse(&format!("file:///{}", self.path))
+                .ok()
+                .and_then(|url| url.to_file_path().ok())
+                .with_context(|| format!("Failed to resolve output directory: {}", self.path))?;
+            std::fs::create_dir_all(&dir)
+                .with_context(|| format!("Failed to create output directory: {}", self.path))?;
+        }
+        Ok(())
+    }
+
+    pub async fn get_bytes(&self) -> Result<Bytes> {
+        let result = self
+            .store
+            .get(&self.path)
+            .await
+            .with_context(|| format!("Failed to fetch {}", self.path))?;
+        result
+            .bytes()
+            .await
+            .with_context(|| format!("Failed to read {}", self.path))
+    }
+
+    pub async fn exists(&self) -> Result<bool> {
+        match self.store.head(&self.path).await {
+            Ok(_) => Ok(true),
+            Err(object_store::Error::NotFound { .. }) => Ok(false),
+            Err(e) => Err(e).with_context(|| format!("Failed to stat {}", self.path)),
+        }
+    }
+
+    pub async fn put_bytes(&self, data: Bytes) -> Result<()> {
+        self.store
+            .put(&self.path, data.into())
+            .await
+            .with_context(|| format!("Failed to write {}", self.path))?;
+        Ok(())
+    }
+
+    /// List the immediate contents of this location, non-recursively (no
+    /// descent into nested "subdirectories"), matching the baseline's
+    /// `read_dir`-based behavior.
+    pub async fn list(&self) -> Result<Vec<Location>> {
+        let prefix = if self.path.parts().count() == 0 {
+            None
+        } else {
+            Some(&self.path)
+        };
+
+        // `list` recurses into every nested prefix; `list_with_delimiter`
+        // stops at the first level and reports deeper prefixes separately
+        // (in `common_prefixes`, which we ignore here).
+        let result = self
+            .store
+            .list_with_delimiter(prefix)
+            .await
+            .with_context(|| format!("Failed to list {}", self.path))?;
+
+        Ok(result
+            .objects
+            .into_iter()
+            .map(|meta| Self {
+                store: Arc::clone(&self.store),
+                path: meta.location,
+                is_local: self.is_local,
+            })
+            .collect())
+    }
+
+    pub fn extension(&self) -> Option<String> {
+        self.path.extension().map(str::to_owned)
+    }
+
+    pub fn file_name(&self) -> Option<String> {
+        self.path.filename().map(str::to_owned)
+    }
+}
+
+impl std::fmt::Display for Location {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.path)
+    }
+}